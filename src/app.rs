@@ -0,0 +1,593 @@
+// Copyright (c) Jørgen Tjernø <jorgen@tjer.no>. All rights reserved.
+use crate::platform::{self, Platform, ProtocolRegistrar, Registration, Rule};
+use crate::resolver::{self, ResolvedAction};
+use crate::rules;
+use anyhow::{anyhow, bail, Context, Result};
+use log::{info, trace};
+use serde::Serialize;
+use simplelog::*;
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+use structopt::StructOpt;
+use time::macros::format_description;
+
+const DISPLAY_NAME: &str = "Hermes URL Handler";
+const DESCRIPTION: &str = "Open links to UE4 assets or custom editor actions";
+
+/// Combine the path and query string from the given Url
+fn get_path_and_extras(url: &url::Url) -> String {
+    let mut path = url.path().to_owned();
+
+    if let Some(query) = url.query() {
+        path += "?";
+        path += query;
+    }
+
+    path
+}
+
+/// Spawn `exe_name` with `args`, logging the fully shell-quoted command line first so it can be
+/// copy-pasted when diagnosing a misbehaving handler.
+fn spawn_command(exe_name: &str, args: &[String]) -> Result<()> {
+    info!(
+        "executing: {}",
+        shell_words::join(std::iter::once(exe_name).chain(args.iter().map(String::as_str)))
+    );
+    Command::new(exe_name)
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .stdin(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to execute {:?} {:?}", exe_name, args))?;
+    Ok(())
+}
+
+/// Substitute `%1` for `full_path` in `protocol_command` and launch it.
+fn spawn_registered_command(protocol_command: Vec<String>, full_path: &str) -> Result<()> {
+    let mut protocol_command = protocol_command.into_iter();
+    let exe_name = protocol_command
+        .next()
+        .ok_or_else(|| anyhow!("empty command specified"))?;
+
+    // TODO: Handle %%1 as an escape?
+    let args: Vec<_> = protocol_command
+        .map(|arg: String| arg.replace("%1", full_path))
+        .collect();
+
+    spawn_command(&exe_name, &args)
+}
+
+/// Dispatch the given URL to the platform's IPC endpoint for it or launch the editor
+fn open_url(platform: &impl Platform, url: &str) -> Result<()> {
+    let url = url::Url::parse(url)?;
+    let protocol = url.scheme();
+    let hostname = url
+        .host_str()
+        .ok_or_else(|| anyhow!("could not parse hostname from {}", url))?;
+    let path = get_path_and_extras(&url);
+    let full_path = format!("/{}{}", hostname, path);
+    trace!(
+        "split url {} into protocol={}, full_path={} (hostname={} + path={})",
+        url,
+        protocol,
+        full_path,
+        hostname,
+        path
+    );
+
+    // Allow any process to steal focus from us, so that we will transfer focus "nicely" to
+    // Unreal.
+    platform.allow_foreground_handoff();
+
+    let Registration { rules, resolver: is_resolver } = platform.load_registration(protocol)?;
+    let command = rules::select(&rules, &url)?.to_vec();
+    trace!("picked command for {}: {:?} (resolver={})", protocol, command, is_resolver);
+
+    if is_resolver {
+        match resolver::resolve(&command, &url, &full_path)
+            .with_context(|| format!("resolver failed for url {}", url))?
+        {
+            ResolvedAction::Forward(endpoint) => {
+                if !platform.try_forward_to(&endpoint, &full_path) {
+                    bail!(
+                        "resolver directed {} to {}, but nothing is listening there",
+                        url,
+                        endpoint
+                    );
+                }
+                return Ok(());
+            }
+            ResolvedAction::Launch(command) => {
+                let mut command = command.into_iter();
+                let exe_name = command
+                    .next()
+                    .ok_or_else(|| anyhow!("resolver returned an empty command for {}", url))?;
+                let args: Vec<_> = command.collect();
+                return spawn_command(&exe_name, &args);
+            }
+            ResolvedAction::Fallback => {
+                trace!("resolver fell back to the static command for {}", url);
+            }
+        }
+    }
+
+    let could_send = platform.try_forward(protocol, &full_path);
+    if !could_send {
+        spawn_registered_command(command, &full_path)?;
+    }
+
+    Ok(())
+}
+
+/// Validate the scheme according to RFC3986 (https://datatracker.ietf.org/doc/html/rfc3986)
+fn parse_scheme(src: &str) -> Result<String, anyhow::Error> {
+    let src = src.trim();
+    let mut chars = src.chars();
+    let first_char = chars
+        .next()
+        .ok_or_else(|| anyhow!("protocol needs to contain at least one character"))?;
+    if !first_char.is_ascii_alphabetic() {
+        bail!(
+            "protocol '{}' needs to start with an alphabetic character",
+            src
+        );
+    }
+
+    for char in chars {
+        if !char.is_ascii_alphanumeric() && char != '+' && char != '-' && char != '.' {
+            bail!("protocol '{}' can only contain the letters a-z, the numbers 0-9, '+', '-', and '.'", src);
+        }
+    }
+
+    Ok(src.to_lowercase())
+}
+
+// This is the definition of our command line options
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = DISPLAY_NAME,
+    about = DESCRIPTION
+)]
+struct CommandOptions {
+    /// Use verbose logging
+    #[structopt(short, long)]
+    verbose: bool,
+    /// Use debug logging, even more verbose than --verbose
+    #[structopt(long)]
+    debug: bool,
+
+    /// Maximum size, in bytes, hermes.log is allowed to reach before it's rotated
+    #[structopt(long, default_value = "1048576")]
+    max_log_size: u64,
+    /// Maximum number of rotated log generations (hermes.log.1, hermes.log.2, ...) to keep
+    #[structopt(long, default_value = "7")]
+    max_log_files: u32,
+
+    /// Choose the mode of operation
+    #[structopt(subcommand)]
+    mode: ExecutionMode,
+}
+
+#[derive(Debug, StructOpt)]
+enum ExecutionMode {
+    /// Dispatch the given URL to Unreal Engine (or launch it, if needed)
+    Open {
+        /// URL to open
+        url: String,
+    },
+
+    /// Register this EXE as a URL protocol handler
+    Register {
+        /// The protocol this exe will be registered for
+        #[structopt(parse(try_from_str = parse_scheme))]
+        protocol: String,
+        /// Enable debug logging for this registration
+        #[structopt(long)]
+        register_with_debugging: bool,
+        /// Treat the picked command as an external resolver, invoked once per URL over a JSON
+        /// stdio protocol, instead of launching it directly (with %1 substituted)
+        #[structopt(long)]
+        resolver: bool,
+        /// The command line that will handle the registration if needed, where %1 is the
+        /// placeholder for the path. Used as the implicit, always-matching fallback rule; for
+        /// per-URL routing, pass one or more `--rule '<expr>' -- <commandline> --` groups before
+        /// it instead (parsed out of the raw arguments before this, see `extract_rule_args`)
+        commandline: Vec<String>,
+    },
+
+    /// Remove the URL protocol handler registration & its stored configuration
+    Unregister {
+        /// The protocol we will delete the registration for
+        #[structopt(parse(try_from_str = parse_scheme))]
+        protocol: String,
+    },
+
+    /// Audit every registered protocol and print a one-shot diagnostic dump: whether its
+    /// registration still points at this exe, whether anything is listening for it, and the
+    /// tail of hermes.log
+    Doctor {
+        /// Emit the report as JSON instead of a human-readable summary
+        #[structopt(long)]
+        json: bool,
+    },
+}
+
+/// Pull repeated `--rule '<expr>' -- <commandline> --` groups out of `args`, leaving the rest
+/// for structopt to parse as usual. clap only understands a single trailing `--`, so rule groups
+/// have to be extracted by hand before we hand the remaining arguments to `CommandOptions`. Each
+/// group's commandline must be closed with its own trailing `--`, so that whatever follows it
+/// (another `--rule` group, or a plain fallback commandline) can't be silently swallowed into it.
+fn extract_rule_args(args: Vec<String>) -> Result<(Vec<String>, Vec<Rule>)> {
+    let mut remaining = Vec::with_capacity(args.len());
+    let mut rules = Vec::new();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        if arg != "--rule" {
+            remaining.push(arg);
+            continue;
+        }
+
+        let match_expr = args
+            .next()
+            .ok_or_else(|| anyhow!("--rule requires a match expression"))?;
+        let separator = args
+            .next()
+            .ok_or_else(|| anyhow!("--rule '{}' must be followed by -- <commandline> --", match_expr))?;
+        if separator != "--" {
+            bail!(
+                "--rule '{}' must be followed by -- <commandline> --, found {:?}",
+                match_expr,
+                separator
+            );
+        }
+
+        let mut command = Vec::new();
+        let mut closed = false;
+        while let Some(next) = args.next() {
+            if next == "--" {
+                closed = true;
+                break;
+            }
+            command.push(next);
+        }
+        if !closed {
+            bail!(
+                "--rule '{}' must be closed with a trailing --, so its commandline doesn't swallow whatever follows it",
+                match_expr
+            );
+        }
+        if command.is_empty() {
+            bail!("--rule '{}' has no commandline", match_expr);
+        }
+
+        // Validate eagerly, so a typo in the expression is reported before we try to register it.
+        rules::parse(&match_expr).with_context(|| format!("invalid --rule expression '{}'", match_expr))?;
+
+        rules.push(Rule {
+            match_expr: Some(match_expr),
+            command,
+        });
+    }
+
+    Ok((remaining, rules))
+}
+
+fn get_exe_relative_path(filename: &str) -> io::Result<PathBuf> {
+    let mut path = std::env::current_exe()?;
+    path.set_file_name(filename);
+    Ok(path)
+}
+
+/// Path of the `generation`-th rotated log (`hermes.log.1`, `hermes.log.2`, ...), or `log_path`
+/// itself for generation 0.
+fn log_generation_path(log_path: &Path, generation: u32) -> PathBuf {
+    if generation == 0 {
+        log_path.to_owned()
+    } else {
+        log_path.with_extension(format!("log.{}", generation))
+    }
+}
+
+/// Shift `hermes.log.(k)` to `hermes.log.(k+1)` for every generation we keep, dropping whatever
+/// was in the oldest slot, then move the active log into `hermes.log.1`.
+fn rotate_log_generations(log_path: &Path, max_files: u32) {
+    let _ = std::fs::remove_file(log_generation_path(log_path, max_files));
+
+    for generation in (1..max_files).rev() {
+        let _ = std::fs::rename(
+            log_generation_path(log_path, generation),
+            log_generation_path(log_path, generation + 1),
+        );
+    }
+
+    if max_files > 0 {
+        let _ = std::fs::rename(log_path, log_generation_path(log_path, 1));
+    } else {
+        let _ = std::fs::remove_file(log_path);
+    }
+}
+
+fn rotate_and_open_log(log_path: &Path, max_size: u64, max_files: u32) -> Result<File, io::Error> {
+    if let Ok(log_info) = std::fs::metadata(log_path) {
+        if log_info.len() > max_size {
+            rotate_log_generations(log_path, max_files);
+        }
+    }
+
+    OpenOptions::new().append(true).create(true).open(log_path)
+}
+
+/// Millisecond-precision, local-time log record format, so a user reading hermes.log can line
+/// entries up against when they clicked a link.
+fn log_config() -> Config {
+    let mut builder = ConfigBuilder::new();
+    builder.set_time_format_custom(format_description!(
+        "[year]/[month]/[day] [hour]:[minute]:[second].[subsecond digits:3]"
+    ));
+    let _ = builder.set_time_offset_to_local();
+    builder.build()
+}
+
+fn init(args: Vec<String>) -> Result<CommandOptions> {
+    // First parse our command line options, so we can use it to configure the logging.
+    let options = CommandOptions::from_iter(args);
+    let log_level = if options.debug {
+        LevelFilter::Trace
+    } else if options.verbose {
+        LevelFilter::Debug
+    } else {
+        LevelFilter::Info
+    };
+
+    let mut loggers: Vec<Box<dyn SharedLogger>> = Vec::new();
+
+    // Always log to hermes.log
+    let log_path = get_exe_relative_path("hermes.log")?;
+    loggers.push(WriteLogger::new(
+        log_level,
+        log_config(),
+        rotate_and_open_log(&log_path, options.max_log_size, options.max_log_files)?,
+    ));
+
+    // We only use the terminal logger in the debug build, since we don't allocate a console window otherwise.
+    if cfg!(debug_assertions) {
+        loggers.push(TermLogger::new(
+            log_level,
+            log_config(),
+            TerminalMode::Mixed,
+            ColorChoice::Auto,
+        ));
+    };
+
+    CombinedLogger::init(loggers)?;
+    trace!("command line options: {:?}", options);
+
+    Ok(options)
+}
+
+/// Health of a single registered protocol, as reported by `doctor`.
+#[derive(Debug, Serialize)]
+struct ProtocolHealth {
+    protocol: String,
+    resolver: bool,
+    rule_count: usize,
+    registration_current: bool,
+    listener_active: bool,
+}
+
+/// Full `doctor` report: one [`ProtocolHealth`] per registered protocol, plus enough of the log
+/// to make a bug report actionable without asking the user to go dig it up themselves.
+#[derive(Debug, Serialize)]
+struct DoctorReport {
+    log_path: String,
+    recent_log_lines: Vec<String>,
+    protocols: Vec<ProtocolHealth>,
+}
+
+/// The last `max_lines` lines of the file at `path`, or an empty list if it can't be read.
+fn tail_lines(path: &Path, max_lines: usize) -> Vec<String> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(_) => return Vec::new(),
+    };
+
+    let lines: Vec<&str> = contents.lines().collect();
+    let start = lines.len().saturating_sub(max_lines);
+    lines[start..].iter().map(|&line| line.to_owned()).collect()
+}
+
+fn build_doctor_report(platform: &impl Platform, log_path: &Path) -> Result<DoctorReport> {
+    let mut protocols = Vec::new();
+    for protocol in platform.list_protocols()? {
+        let registration = platform
+            .load_registration(&protocol)
+            .with_context(|| format!("failed to load registration for {}://", protocol))?;
+
+        protocols.push(ProtocolHealth {
+            resolver: registration.resolver,
+            rule_count: registration.rules.len(),
+            registration_current: platform.registration_points_here(&protocol),
+            listener_active: platform.probe_listener(&protocol),
+            protocol,
+        });
+    }
+
+    Ok(DoctorReport {
+        log_path: log_path.display().to_string(),
+        recent_log_lines: tail_lines(log_path, 20),
+        protocols,
+    })
+}
+
+fn print_doctor_report(report: &DoctorReport) {
+    println!("log file: {}", report.log_path);
+
+    if report.protocols.is_empty() {
+        println!("no protocols registered");
+    }
+    for protocol in &report.protocols {
+        println!("{}://", protocol.protocol);
+        println!(
+            "  registration: {}",
+            if protocol.registration_current {
+                "ok"
+            } else {
+                "STALE (does not point at this exe)"
+            }
+        );
+        println!(
+            "  rules: {}{}",
+            protocol.rule_count,
+            if protocol.resolver { " (resolver)" } else { "" }
+        );
+        println!(
+            "  listener: {}",
+            if protocol.listener_active {
+                "running"
+            } else {
+                "not running, will launch on next open"
+            }
+        );
+    }
+
+    println!("last {} line(s) of hermes.log:", report.recent_log_lines.len());
+    for line in &report.recent_log_lines {
+        println!("  {}", line);
+    }
+}
+
+fn get_debug_args(register_with_debugging: bool) -> Option<&'static str> {
+    if register_with_debugging {
+        Some("--debug")
+    } else {
+        None
+    }
+}
+
+pub fn main() -> Result<()> {
+    let (args, rule_args) = extract_rule_args(std::env::args().collect())?;
+    let options = init(args)?;
+    trace!(
+        "running from directory {}",
+        std::env::current_dir().unwrap_or_default().display()
+    );
+
+    let platform = platform::current();
+
+    match options.mode {
+        ExecutionMode::Register {
+            protocol,
+            commandline,
+            register_with_debugging,
+            resolver,
+        } => {
+            let mut rules = rule_args;
+            if !commandline.is_empty() {
+                rules.push(Rule {
+                    match_expr: None,
+                    command: commandline,
+                });
+            }
+            if rules.is_empty() {
+                bail!("register requires either a commandline or at least one --rule");
+            }
+
+            platform
+                .register(
+                    &protocol,
+                    &rules,
+                    resolver,
+                    get_debug_args(register_with_debugging),
+                )
+                .with_context(|| format!("Failed to register command for {}://", protocol))?;
+        }
+        ExecutionMode::Unregister { protocol } => {
+            info!("unregistering handler for {}://", protocol);
+            platform.unregister(&protocol);
+        }
+        ExecutionMode::Open { url } => {
+            open_url(&platform, &url).with_context(|| format!("Failed to open url {}", url))?;
+        }
+        ExecutionMode::Doctor { json } => {
+            let log_path = get_exe_relative_path("hermes.log")?;
+            let report = build_doctor_report(&platform, &log_path)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                print_doctor_report(&report);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strs(args: &[&str]) -> Vec<String> {
+        args.iter().map(|&s| s.to_owned()).collect()
+    }
+
+    #[test]
+    fn extract_rule_args_leaves_non_rule_args_untouched() {
+        let (remaining, rules) = extract_rule_args(strs(&["register", "proto", "/bin/editor"])).unwrap();
+        assert_eq!(remaining, strs(&["register", "proto", "/bin/editor"]));
+        assert!(rules.is_empty());
+    }
+
+    #[test]
+    fn extract_rule_args_parses_a_single_closed_group() {
+        let (remaining, rules) = extract_rule_args(strs(&[
+            "register", "proto", "--rule", "host = \"a.com\"", "--", "/bin/editor", "--",
+        ]))
+        .unwrap();
+        assert_eq!(remaining, strs(&["register", "proto"]));
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].match_expr.as_deref(), Some("host = \"a.com\""));
+        assert_eq!(rules[0].command, strs(&["/bin/editor"]));
+    }
+
+    #[test]
+    fn extract_rule_args_keeps_a_trailing_fallback_separate_from_the_last_rule() {
+        let (remaining, rules) = extract_rule_args(strs(&[
+            "register",
+            "proto",
+            "--rule",
+            "host = \"a.com\"",
+            "--",
+            "/bin/editor",
+            "--",
+            "--rule",
+            "host = \"b.com\"",
+            "--",
+            "/bin/editor2",
+            "--",
+            "/bin/default",
+        ]))
+        .unwrap();
+        assert_eq!(remaining, strs(&["register", "proto", "/bin/default"]));
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[1].command, strs(&["/bin/editor2"]));
+    }
+
+    #[test]
+    fn extract_rule_args_rejects_an_unclosed_group() {
+        let result = extract_rule_args(strs(&[
+            "register",
+            "proto",
+            "--rule",
+            "host = \"a.com\"",
+            "--",
+            "/bin/editor",
+        ]));
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,9 @@
+// Copyright (c) Jørgen Tjernø <jorgen@tjer.no>. All rights reserved.
+mod app;
+mod platform;
+mod resolver;
+mod rules;
+
+fn main() -> anyhow::Result<()> {
+    app::main()
+}
@@ -0,0 +1,60 @@
+// Copyright (c) Jørgen Tjernø <jorgen@tjer.no>. All rights reserved.
+//! Simple file-backed JSON storage for a protocol's [`super::Registration`], shared by the
+//! platforms that don't have a registry to put it in.
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+fn entry_path(dir: &Path, protocol: &str) -> PathBuf {
+    dir.join(format!("{}.json", protocol))
+}
+
+/// Persist `value` as JSON under `dir`, creating it if needed.
+pub fn store<T: Serialize>(dir: &Path, protocol: &str, value: &T) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+
+    let path = entry_path(dir, protocol);
+    let contents = serde_json::to_string_pretty(value)?;
+    fs::write(&path, contents).with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Load whatever was stored for `protocol`, if anything.
+pub fn load<T: DeserializeOwned>(dir: &Path, protocol: &str) -> Result<T> {
+    let path = entry_path(dir, protocol);
+    let contents = fs::read_to_string(&path)
+        .with_context(|| format!("no command registered for protocol {}", protocol))?;
+    serde_json::from_str(&contents).with_context(|| format!("failed to parse {}", path.display()))
+}
+
+/// Remove whatever was stored for `protocol`, if anything.
+pub fn remove(dir: &Path, protocol: &str) {
+    let _ = fs::remove_file(entry_path(dir, protocol));
+}
+
+/// List every protocol with a stored registration under `dir`.
+pub fn list_protocols(dir: &Path) -> Result<Vec<String>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => {
+            return Err(error).with_context(|| format!("failed to read {}", dir.display()))
+        }
+    };
+
+    let mut protocols: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.extension()?.to_str()? == "json" {
+                path.file_stem()?.to_str().map(str::to_owned)
+            } else {
+                None
+            }
+        })
+        .collect();
+    protocols.sort();
+    Ok(protocols)
+}
@@ -0,0 +1,173 @@
+// Copyright (c) Jørgen Tjernø <jorgen@tjer.no>. All rights reserved.
+use super::{config_store, unix_ipc, Diagnostics, IpcForwarder, ProtocolRegistrar, Registration, Rule};
+use anyhow::{Context, Result};
+use log::{debug, info, trace, warn};
+use std::{
+    env::current_exe,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+fn data_home() -> PathBuf {
+    std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(".local/share")
+        })
+}
+
+fn runtime_dir() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+fn config_dir() -> PathBuf {
+    data_home().join("hermes/protocols")
+}
+
+fn desktop_file_path(protocol: &str) -> PathBuf {
+    data_home()
+        .join("applications")
+        .join(format!("hermes-{}.desktop", protocol))
+}
+
+fn socket_path(protocol: &str) -> PathBuf {
+    runtime_dir().join(format!("hermes-{}.sock", protocol))
+}
+
+fn run_update_desktop_database() {
+    let applications_dir = data_home().join("applications");
+    match Command::new("update-desktop-database")
+        .arg(&applications_dir)
+        .status()
+    {
+        Ok(status) if !status.success() => {
+            warn!("update-desktop-database exited with {}", status)
+        }
+        Err(error) => warn!("failed to run update-desktop-database: {}", error),
+        Ok(_) => {}
+    }
+}
+
+fn run_xdg_mime_default(desktop_file_name: &str, protocol: &str) {
+    let mime_type = format!("x-scheme-handler/{}", protocol);
+    match Command::new("xdg-mime")
+        .args(["default", desktop_file_name, &mime_type])
+        .status()
+    {
+        Ok(status) if !status.success() => warn!("xdg-mime exited with {}", status),
+        Err(error) => warn!("failed to run xdg-mime: {}", error),
+        Ok(_) => {}
+    }
+}
+
+#[derive(Default)]
+pub struct LinuxPlatform;
+
+impl ProtocolRegistrar for LinuxPlatform {
+    /// Register a `hermes-<scheme>.desktop` file exposing `x-scheme-handler/<scheme>`, and make
+    /// it the default handler for the scheme.
+    fn register(
+        &self,
+        protocol: &str,
+        rules: &[Rule],
+        resolver: bool,
+        extra_args: Option<&str>,
+    ) -> Result<()> {
+        let exe_path = current_exe()?;
+        let exe_path = exe_path.to_str().unwrap_or_default();
+        let extra = extra_args.map(|args| format!(" {}", args)).unwrap_or_default();
+
+        let desktop_path = desktop_file_path(protocol);
+        if let Some(parent) = desktop_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+
+        let contents = format!(
+            "[Desktop Entry]\n\
+             Type=Application\n\
+             Name=Hermes URL Handler ({protocol})\n\
+             Exec={exe}{extra} open %u\n\
+             NoDisplay=true\n\
+             MimeType=x-scheme-handler/{protocol};\n",
+            protocol = protocol,
+            exe = exe_path,
+            extra = extra,
+        );
+        fs::write(&desktop_path, contents)
+            .with_context(|| format!("failed to write {}", desktop_path.display()))?;
+        debug!("wrote {}", desktop_path.display());
+
+        info!("registering command for {}://", protocol);
+        run_update_desktop_database();
+        let desktop_file_name = desktop_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        run_xdg_mime_default(desktop_file_name, protocol);
+
+        let registration = Registration {
+            rules: rules.to_vec(),
+            resolver,
+        };
+        config_store::store(&config_dir(), protocol, &registration)
+    }
+
+    /// Remove the desktop file and stored configuration for a protocol
+    fn unregister(&self, protocol: &str) {
+        let desktop_path = desktop_file_path(protocol);
+        if desktop_path.exists() {
+            info!("removing protocol registration for {}://", protocol);
+            if let Err(error) = fs::remove_file(&desktop_path) {
+                warn!("unable to delete {}: {}", desktop_path.display(), error);
+            }
+            run_update_desktop_database();
+        } else {
+            trace!(
+                "could not find {}, assuming it doesn't exist",
+                desktop_path.display()
+            );
+        }
+
+        config_store::remove(&config_dir(), protocol);
+    }
+
+    fn load_registration(&self, protocol: &str) -> Result<Registration> {
+        config_store::load(&config_dir(), protocol)
+    }
+}
+
+impl IpcForwarder for LinuxPlatform {
+    fn try_forward(&self, protocol: &str, full_path: &str) -> bool {
+        unix_ipc::send_to_socket(&socket_path(protocol), full_path)
+    }
+
+    fn try_forward_to(&self, endpoint: &str, full_path: &str) -> bool {
+        unix_ipc::send_to_socket(Path::new(endpoint), full_path)
+    }
+}
+
+impl Diagnostics for LinuxPlatform {
+    fn list_protocols(&self) -> Result<Vec<String>> {
+        config_store::list_protocols(&config_dir())
+    }
+
+    /// Check whether the `.desktop` file's `Exec=` line still invokes the exe we're running as.
+    fn registration_points_here(&self, protocol: &str) -> bool {
+        let exe_path = match current_exe().ok().and_then(|path| path.to_str().map(str::to_owned)) {
+            Some(path) => path,
+            None => return false,
+        };
+
+        fs::read_to_string(desktop_file_path(protocol))
+            .map(|contents| contents.contains(&exe_path))
+            .unwrap_or(false)
+    }
+
+    fn probe_listener(&self, protocol: &str) -> bool {
+        unix_ipc::probe_socket(&socket_path(protocol))
+    }
+}
@@ -0,0 +1,187 @@
+// Copyright (c) Jørgen Tjernø <jorgen@tjer.no>. All rights reserved.
+use super::{config_store, unix_ipc, Diagnostics, IpcForwarder, ProtocolRegistrar, Registration, Rule};
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info, trace, warn};
+use std::{
+    env::current_exe,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const LSREGISTER: &str =
+    "/System/Library/Frameworks/CoreServices.framework/Versions/A/Frameworks/LaunchServices.framework/Versions/A/Support/lsregister";
+
+fn config_dir() -> PathBuf {
+    PathBuf::from(std::env::var_os("HOME").unwrap_or_default())
+        .join("Library/Application Support/Hermes/Protocols")
+}
+
+fn socket_path(protocol: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("hermes-{}.sock", protocol))
+}
+
+/// Walk up from the running executable to find the `.app` bundle it lives in.
+fn find_bundle(exe_path: &Path) -> Result<PathBuf> {
+    exe_path
+        .ancestors()
+        .find(|ancestor| ancestor.extension().map_or(false, |ext| ext == "app"))
+        .map(Path::to_path_buf)
+        .ok_or_else(|| anyhow!("{} is not running from inside an .app bundle", exe_path.display()))
+}
+
+/// Drop any existing `CFBundleURLTypes` entry for `protocol`, then let `add` push a replacement
+/// (if any), and write the bundle's `Info.plist` back out.
+fn update_url_types(
+    bundle_path: &Path,
+    protocol: &str,
+    add: impl FnOnce(&mut Vec<plist::Value>, &str),
+) -> Result<()> {
+    let plist_path = bundle_path.join("Contents/Info.plist");
+    let url_name = format!("no.tjer.hermes.{}", protocol);
+
+    let mut info = plist::Value::from_file(&plist_path)
+        .with_context(|| format!("failed to read {}", plist_path.display()))?
+        .into_dictionary()
+        .ok_or_else(|| anyhow!("{} does not contain a dictionary", plist_path.display()))?;
+
+    let mut url_types = info
+        .remove("CFBundleURLTypes")
+        .and_then(plist::Value::into_array)
+        .unwrap_or_default();
+
+    url_types.retain(|entry| {
+        entry
+            .as_dictionary()
+            .and_then(|dict| dict.get("CFBundleURLName"))
+            .and_then(plist::Value::as_string)
+            != Some(url_name.as_str())
+    });
+
+    add(&mut url_types, &url_name);
+    info.insert("CFBundleURLTypes".to_owned(), plist::Value::Array(url_types));
+
+    plist::Value::Dictionary(info)
+        .to_file_xml(&plist_path)
+        .with_context(|| format!("failed to write {}", plist_path.display()))
+}
+
+fn run_lsregister(bundle_path: &Path) {
+    match Command::new(LSREGISTER).arg("-f").arg(bundle_path).status() {
+        Ok(status) if !status.success() => warn!("lsregister exited with {}", status),
+        Err(error) => warn!("failed to run lsregister: {}", error),
+        Ok(_) => {}
+    }
+}
+
+#[derive(Default)]
+pub struct MacPlatform;
+
+impl ProtocolRegistrar for MacPlatform {
+    /// Register `protocol` with Launch Services by updating our bundle's `Info.plist` and
+    /// re-running `lsregister` against it. `extra_args` is not used here: nothing in the bundle
+    /// records arguments to launch our exe with.
+    fn register(
+        &self,
+        protocol: &str,
+        rules: &[Rule],
+        resolver: bool,
+        _extra_args: Option<&str>,
+    ) -> Result<()> {
+        let exe_path = current_exe()?;
+        let bundle_path = find_bundle(&exe_path)?;
+
+        info!("registering command for {}://", protocol);
+        update_url_types(&bundle_path, protocol, |url_types, url_name| {
+            let mut entry = plist::Dictionary::new();
+            entry.insert("CFBundleURLName".to_owned(), url_name.to_owned().into());
+            entry.insert(
+                "CFBundleURLSchemes".to_owned(),
+                plist::Value::Array(vec![protocol.to_owned().into()]),
+            );
+            url_types.push(plist::Value::Dictionary(entry));
+        })?;
+        run_lsregister(&bundle_path);
+        debug!(
+            "registered {}:// with Launch Services via {}",
+            protocol,
+            bundle_path.display()
+        );
+
+        let registration = Registration {
+            rules: rules.to_vec(),
+            resolver,
+        };
+        config_store::store(&config_dir(), protocol, &registration)
+    }
+
+    /// Remove the `CFBundleURLTypes` entry and stored configuration for a protocol
+    fn unregister(&self, protocol: &str) {
+        match current_exe().and_then(|exe| find_bundle(&exe).map_err(Into::into)) {
+            Ok(bundle_path) => {
+                info!("removing protocol registration for {}://", protocol);
+                if let Err(error) = update_url_types(&bundle_path, protocol, |_, _| {}) {
+                    warn!("unable to update {}: {}", bundle_path.display(), error);
+                } else {
+                    run_lsregister(&bundle_path);
+                }
+            }
+            Err(error) => trace!(
+                "could not locate our .app bundle, skipping Launch Services cleanup: {}",
+                error
+            ),
+        }
+
+        config_store::remove(&config_dir(), protocol);
+    }
+
+    fn load_registration(&self, protocol: &str) -> Result<Registration> {
+        config_store::load(&config_dir(), protocol)
+    }
+}
+
+impl IpcForwarder for MacPlatform {
+    fn try_forward(&self, protocol: &str, full_path: &str) -> bool {
+        unix_ipc::send_to_socket(&socket_path(protocol), full_path)
+    }
+
+    fn try_forward_to(&self, endpoint: &str, full_path: &str) -> bool {
+        unix_ipc::send_to_socket(Path::new(endpoint), full_path)
+    }
+}
+
+impl Diagnostics for MacPlatform {
+    fn list_protocols(&self) -> Result<Vec<String>> {
+        config_store::list_protocols(&config_dir())
+    }
+
+    /// Check whether `Info.plist` still has a `CFBundleURLTypes` entry for `protocol`. The entry
+    /// lives inside the bundle itself, so it can only go stale if the bundle was recreated
+    /// (e.g. rebuilt) without re-registering.
+    fn registration_points_here(&self, protocol: &str) -> bool {
+        let url_name = format!("no.tjer.hermes.{}", protocol);
+
+        current_exe()
+            .ok()
+            .and_then(|exe| find_bundle(&exe).ok())
+            .and_then(|bundle_path| {
+                plist::Value::from_file(bundle_path.join("Contents/Info.plist")).ok()
+            })
+            .and_then(plist::Value::into_dictionary)
+            .and_then(|info| info.get("CFBundleURLTypes").cloned())
+            .and_then(plist::Value::into_array)
+            .map(|url_types| {
+                url_types.iter().any(|entry| {
+                    entry
+                        .as_dictionary()
+                        .and_then(|dict| dict.get("CFBundleURLName"))
+                        .and_then(plist::Value::as_string)
+                        == Some(url_name.as_str())
+                })
+            })
+            .unwrap_or(false)
+    }
+
+    fn probe_listener(&self, protocol: &str) -> bool {
+        unix_ipc::probe_socket(&socket_path(protocol))
+    }
+}
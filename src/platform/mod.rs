@@ -0,0 +1,107 @@
+// Copyright (c) Jørgen Tjernø <jorgen@tjer.no>. All rights reserved.
+//! Per-platform backends for registering URL protocol handlers and forwarding dispatched URLs
+//! to an already-running instance of the target application.
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+mod config_store;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod unix_ipc;
+
+#[cfg(target_os = "windows")]
+mod windows;
+#[cfg(target_os = "windows")]
+pub use self::windows::WindowsPlatform as CurrentPlatform;
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "linux")]
+pub use linux::LinuxPlatform as CurrentPlatform;
+
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(target_os = "macos")]
+pub use macos::MacPlatform as CurrentPlatform;
+
+/// One entry in a protocol's ordered rule list: a command line, and the expression (parsed by
+/// [`crate::rules`]) that a URL has to match for it to be picked. `match_expr` is `None` for the
+/// implicit, always-matching fallback rule.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rule {
+    #[serde(rename = "match", default, skip_serializing_if = "Option::is_none")]
+    pub match_expr: Option<String>,
+    pub command: Vec<String>,
+}
+
+/// Everything we remember about a protocol registration: the ordered rules used to pick a
+/// command for a URL, and whether the picked command should be treated as a resolver (see
+/// [`crate::resolver`]) instead of being launched directly.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Registration {
+    pub rules: Vec<Rule>,
+    #[serde(default)]
+    pub resolver: bool,
+}
+
+/// Registers (and unregisters) Hermes as the handler for a custom URL scheme, and remembers the
+/// rules that should be used to pick a command for it.
+pub trait ProtocolRegistrar {
+    /// Register `protocol` so that URLs using it are dispatched to us, and remember `rules`
+    /// (invoking our own exe with `extra_args`, if any, inserted before `open`) so a later
+    /// `load_registration` can retrieve them. `resolver` marks the picked command as an external
+    /// resolver rather than a direct handler, see [`crate::resolver`].
+    fn register(
+        &self,
+        protocol: &str,
+        rules: &[Rule],
+        resolver: bool,
+        extra_args: Option<&str>,
+    ) -> Result<()>;
+
+    /// Remove whatever registration and configuration we previously set up for `protocol`.
+    fn unregister(&self, protocol: &str);
+
+    /// Look up the registration stored for `protocol`.
+    fn load_registration(&self, protocol: &str) -> Result<Registration>;
+}
+
+/// Forwards a dispatched URL to an already-running instance of the target application, so we
+/// only launch a new one when nothing is listening.
+pub trait IpcForwarder {
+    /// Attempt to forward `full_path` to a running instance registered for `protocol`. Returns
+    /// `true` if the message was handed off successfully, `false` if we should fall back to
+    /// launching the configured command ourselves.
+    fn try_forward(&self, protocol: &str, full_path: &str) -> bool;
+
+    /// Attempt to forward `full_path` to a specific IPC endpoint (as returned by a resolver's
+    /// `forward` action), rather than the default endpoint for `protocol`.
+    fn try_forward_to(&self, endpoint: &str, full_path: &str) -> bool;
+
+    /// Platforms that track foreground-window focus should override this to let the process we
+    /// are about to launch or forward to steal focus without the usual restrictions.
+    fn allow_foreground_handoff(&self) {}
+}
+
+/// Non-destructive self-checks backing the `doctor` subcommand, so a user can get a one-shot
+/// diagnostic dump without us actually delivering a URL anywhere.
+pub trait Diagnostics {
+    /// Every protocol we currently have a registration stored for.
+    fn list_protocols(&self) -> Result<Vec<String>>;
+
+    /// Whether the OS-level registration for `protocol` still points at the exe we're running
+    /// as, rather than a path left behind by a move or reinstall.
+    fn registration_points_here(&self, protocol: &str) -> bool;
+
+    /// Whether something is currently listening for `protocol`'s IPC endpoint, without
+    /// delivering anything to it.
+    fn probe_listener(&self, protocol: &str) -> bool;
+}
+
+/// Everything `open_url`, `register` and `unregister` need from the current platform.
+pub trait Platform: ProtocolRegistrar + IpcForwarder + Diagnostics {}
+impl<T: ProtocolRegistrar + IpcForwarder + Diagnostics> Platform for T {}
+
+/// Construct the platform backend for whatever OS we're compiled for.
+pub fn current() -> CurrentPlatform {
+    CurrentPlatform::default()
+}
@@ -0,0 +1,44 @@
+// Copyright (c) Jørgen Tjernø <jorgen@tjer.no>. All rights reserved.
+//! IPC helpers shared by the Linux and macOS backends, both of which dispatch URLs over a Unix
+//! domain socket per protocol.
+use log::{error, trace, warn};
+use std::{io::Write, os::unix::net::UnixStream, path::Path};
+
+/// Connect to the Unix domain socket at `socket` and send `full_path`, returning `true` if it
+/// was delivered.
+pub fn send_to_socket(socket: &Path, full_path: &str) -> bool {
+    trace!("Attempting to send URL over {}", socket.display());
+    match UnixStream::connect(socket) {
+        Ok(mut stream) => {
+            if let Err(error) = stream.write_all(full_path.as_bytes()) {
+                warn!(
+                    "Could not send message to {}: {} -- assuming application is shutting down, starting a new one",
+                    socket.display(),
+                    error
+                );
+                false
+            } else {
+                trace!("Delivered using Unix domain socket");
+                true
+            }
+        }
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            trace!("Socket not found, assuming application is not running");
+            false
+        }
+        Err(error) => {
+            error!(
+                "Could not connect to {}, assuming application is not running: {}",
+                socket.display(),
+                error
+            );
+            false
+        }
+    }
+}
+
+/// Connect to the Unix domain socket at `socket` without writing to it, so we can tell whether a
+/// listener is up without delivering a URL to it.
+pub fn probe_socket(socket: &Path) -> bool {
+    UnixStream::connect(socket).is_ok()
+}
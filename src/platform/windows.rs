@@ -0,0 +1,258 @@
+// Copyright (c) Jørgen Tjernø <jorgen@tjer.no>. All rights reserved.
+use super::{Diagnostics, IpcForwarder, ProtocolRegistrar, Registration, Rule};
+use anyhow::{Context, Result};
+use log::{debug, error, info, trace, warn};
+use mail_slot::{MailslotClient, MailslotName};
+use std::env::current_exe;
+use std::io::ErrorKind;
+use winreg::{enums::*, RegKey};
+
+// Flags needed to run delete_subkey_all as well as just set_value and enum_values on the same handle.
+const ENUMERATE_AND_DELETE_FLAGS: u32 = winreg::enums::KEY_READ | winreg::enums::KEY_SET_VALUE;
+
+fn get_protocol_registry_key(protocol: &str) -> String {
+    format!(r"SOFTWARE\Classes\{}", protocol)
+}
+
+fn get_configuration_registry_key(protocol: &str) -> String {
+    format!(r"Software\bitSpatter\Hermes\Protocols\{}", protocol)
+}
+
+#[derive(Default)]
+pub struct WindowsPlatform;
+
+impl ProtocolRegistrar for WindowsPlatform {
+    /// Register associations with Windows to handle our protocol, and the command we'll invoke
+    fn register(
+        &self,
+        protocol: &str,
+        rules: &[Rule],
+        resolver: bool,
+        extra_args: Option<&str>,
+    ) -> Result<()> {
+        let exe_path = current_exe()?;
+        let exe_path = exe_path.to_str().unwrap_or_default().to_owned();
+        let icon_path = format!("\"{}\",0", exe_path);
+        let open_command = if let Some(extra_args) = extra_args {
+            format!("\"{}\" {} open \"%1\"", exe_path, extra_args)
+        } else {
+            format!("\"{}\" open \"%1\"", exe_path)
+        };
+
+        let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+
+        // Configure our ProgID to point to the right command
+        let protocol_path = get_protocol_registry_key(protocol);
+        let (progid_class, _) = hkcu.create_subkey(&protocol_path)?;
+        progid_class.set_value("", &format!("URL:{} Protocol", protocol))?;
+
+        // Indicates that this class defines a protocol handler
+        progid_class.set_value("URL Protocol", &"")?;
+
+        let (progid_class_defaulticon, _) = progid_class.create_subkey("DefaultIcon")?;
+        progid_class_defaulticon.set_value("", &icon_path)?;
+
+        debug!(
+            r"set HKEY_CURRENT_USER\{}\DefaultIcon to '{}'",
+            protocol_path, icon_path
+        );
+
+        let (progid_class_shell_open_command, _) =
+            progid_class.create_subkey(r"shell\open\command")?;
+        progid_class_shell_open_command.set_value("", &open_command)?;
+
+        debug!(
+            r"set HKEY_CURRENT_USER\{}\shell\open\command to '{}'",
+            protocol_path, open_command
+        );
+
+        info!("registering command for {}://", protocol);
+        let config_path = get_configuration_registry_key(protocol);
+
+        // rule.N.match/rule.N.command are plain values on this key, not subkeys, so the only way
+        // to be sure none survive past a re-registration with fewer rules is to delete and
+        // recreate the key, the same way unregister tears it down entirely.
+        if let Ok(previous_config) =
+            hkcu.open_subkey_with_flags(&config_path, ENUMERATE_AND_DELETE_FLAGS)
+        {
+            let _ = previous_config.delete_subkey_all("");
+        }
+        let _ = hkcu.delete_subkey(&config_path);
+
+        let (config, _) = hkcu.create_subkey(&config_path)?;
+        config.set_value("resolver", &(resolver as u32))?;
+        config.set_value("rule.count", &(rules.len() as u32))?;
+        for (index, rule) in rules.iter().enumerate() {
+            config.set_value(
+                &format!("rule.{}.match", index),
+                &rule.match_expr.clone().unwrap_or_default(),
+            )?;
+            config.set_value(&format!("rule.{}.command", index), &rule.command)?;
+        }
+
+        debug!(
+            r"set HKEY_CURRENT_USER\{}\rule.* to {:?}, resolver to {}",
+            config_path, rules, resolver
+        );
+
+        Ok(())
+    }
+
+    /// Remove all the registry keys that we've set up for a protocol
+    fn unregister(&self, protocol: &str) {
+        let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+
+        let protocol_path = get_protocol_registry_key(protocol);
+        trace!("querying protocol registration at {}", protocol_path);
+        if let Ok(protocol_registry_key) =
+            hkcu.open_subkey_with_flags(&protocol_path, ENUMERATE_AND_DELETE_FLAGS)
+        {
+            info!("removing protocol registration for {}://", protocol);
+
+            let result = protocol_registry_key.delete_subkey_all("");
+            if let Err(error) = result {
+                warn!("unable to delete {}: {}", protocol_path, error);
+            }
+        } else {
+            trace!(
+                "could not open {}, assuming it doesn't exist",
+                protocol_path,
+            );
+        }
+
+        let _ = hkcu.delete_subkey(&protocol_path);
+
+        let configuration_path = get_configuration_registry_key(protocol);
+        trace!("querying configuration at {}", configuration_path);
+        if let Ok(configuration_registry_key) =
+            hkcu.open_subkey_with_flags(&configuration_path, ENUMERATE_AND_DELETE_FLAGS)
+        {
+            info!("removing configuration for {}://", protocol);
+
+            let result = configuration_registry_key.delete_subkey_all("");
+            if let Err(error) = result {
+                warn!("unable to delete {}: {}", configuration_path, error);
+            }
+        } else {
+            trace!(
+                "could not open {}, assuming it doesn't exist",
+                configuration_path,
+            );
+        }
+
+        let _ = hkcu.delete_subkey(&configuration_path);
+    }
+
+    fn load_registration(&self, protocol: &str) -> Result<Registration> {
+        let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+        let config = hkcu
+            .open_subkey(get_configuration_registry_key(protocol))
+            .with_context(|| format!("no hostnames registered for protocol {}", protocol))?;
+        let resolver: u32 = config.get_value("resolver").unwrap_or(0);
+        let rule_count: u32 = config
+            .get_value("rule.count")
+            .with_context(|| format!("no rules registered for protocol {}", protocol))?;
+
+        let mut rules = Vec::with_capacity(rule_count as usize);
+        for index in 0..rule_count {
+            let match_expr: String = config.get_value(format!("rule.{}.match", index))?;
+            let command = config.get_value(format!("rule.{}.command", index))?;
+            rules.push(Rule {
+                match_expr: if match_expr.is_empty() { None } else { Some(match_expr) },
+                command,
+            });
+        }
+
+        Ok(Registration {
+            rules,
+            resolver: resolver != 0,
+        })
+    }
+}
+
+/// Send `full_path` to the named mailslot, returning `true` if it was delivered.
+fn send_to_mailslot(slot_name: &str, full_path: &str) -> bool {
+    let slot = MailslotName::local(slot_name);
+    trace!("Attempting to send URL to mailslot {}", slot.to_string());
+    match MailslotClient::new(&slot) {
+        Ok(mut client) => {
+            if let Err(error) = client.send_message(full_path.as_bytes()) {
+                warn!("Could not send mail slot message to {}: {} -- assuming application is shutting down, starting a new one", slot.to_string(), error);
+                false
+            } else {
+                trace!("Delivered using Mailslot");
+                true
+            }
+        }
+        Err(mail_slot::Error::Io(io_error)) if io_error.kind() == ErrorKind::NotFound => {
+            trace!("Mailslot not found, assuming application is not running");
+            false
+        }
+        Err(err) => {
+            error!(
+                "Could not connect to Mailslot, assuming application is not running: {:?}",
+                err
+            );
+            false
+        }
+    }
+}
+
+impl Diagnostics for WindowsPlatform {
+    fn list_protocols(&self) -> Result<Vec<String>> {
+        let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+        let protocols_key = match hkcu.open_subkey(r"Software\bitSpatter\Hermes\Protocols") {
+            Ok(key) => key,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => {
+                return Err(error).context(r"failed to open Software\bitSpatter\Hermes\Protocols")
+            }
+        };
+
+        let mut protocols: Vec<String> = protocols_key.enum_keys().collect::<Result<_, _>>()?;
+        protocols.sort();
+        Ok(protocols)
+    }
+
+    /// Check whether `SOFTWARE\Classes\<protocol>\shell\open\command` still invokes the exe
+    /// we're running as, rather than a path left behind by a move or reinstall.
+    fn registration_points_here(&self, protocol: &str) -> bool {
+        let exe_path = match current_exe().ok().and_then(|path| path.to_str().map(str::to_owned)) {
+            Some(path) => path,
+            None => return false,
+        };
+
+        let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+        let command_path = format!(r"{}\shell\open\command", get_protocol_registry_key(protocol));
+        hkcu.open_subkey(&command_path)
+            .and_then(|key| key.get_value::<String, _>(""))
+            .map(|command| command.contains(&exe_path))
+            .unwrap_or(false)
+    }
+
+    /// Attempt to open the mailslot without sending anything, so we can tell whether a listener
+    /// is up without delivering a URL to it.
+    fn probe_listener(&self, protocol: &str) -> bool {
+        let slot = MailslotName::local(&format!(r"bitSpatter\Hermes\{}", protocol));
+        MailslotClient::new(&slot).is_ok()
+    }
+}
+
+impl IpcForwarder for WindowsPlatform {
+    fn try_forward(&self, protocol: &str, full_path: &str) -> bool {
+        send_to_mailslot(&format!(r"bitSpatter\Hermes\{}", protocol), full_path)
+    }
+
+    fn try_forward_to(&self, endpoint: &str, full_path: &str) -> bool {
+        send_to_mailslot(endpoint, full_path)
+    }
+
+    /// Allow any process to steal focus from us, so that we will transfer focus "nicely" to
+    /// Unreal.
+    fn allow_foreground_handoff(&self) {
+        use windows::Win32::UI::WindowsAndMessaging::{AllowSetForegroundWindow, ASFW_ANY};
+        unsafe {
+            AllowSetForegroundWindow(ASFW_ANY);
+        }
+    }
+}
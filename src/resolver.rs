@@ -0,0 +1,100 @@
+// Copyright (c) Jørgen Tjernø <jorgen@tjer.no>. All rights reserved.
+//! Support for "resolver" handlers: instead of launching a fixed command, we spawn an external
+//! helper once per URL and ask it, over a small JSON stdio protocol, how to route it.
+use anyhow::{anyhow, bail, Context, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// Protocol version we speak; bump this if the request shape ever changes incompatibly.
+const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize)]
+struct ResolveRequest<'a> {
+    version: u32,
+    scheme: &'a str,
+    host: &'a str,
+    path: &'a str,
+    query: Option<&'a str>,
+    full_path: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+enum ResolveResponse {
+    Forward { mailslot: String },
+    Launch { command: Vec<String> },
+    Error { message: String },
+    Unsupported,
+}
+
+/// What the resolver told us to do with the URL.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ResolvedAction {
+    /// Forward the URL to this specific IPC endpoint.
+    Forward(String),
+    /// Launch this fully-resolved command.
+    Launch(Vec<String>),
+    /// The resolver didn't have an opinion; fall back to the static command behavior.
+    Fallback,
+}
+
+/// Spawn `resolver_command` (exe followed by its arguments), send it the URL broken down as a
+/// JSON request on stdin, and parse its JSON response from stdout.
+pub fn resolve(resolver_command: &[String], url: &url::Url, full_path: &str) -> Result<ResolvedAction> {
+    let (exe, args) = resolver_command
+        .split_first()
+        .ok_or_else(|| anyhow!("resolver command is empty"))?;
+
+    let request = ResolveRequest {
+        version: PROTOCOL_VERSION,
+        scheme: url.scheme(),
+        host: url.host_str().unwrap_or_default(),
+        path: url.path(),
+        query: url.query(),
+        full_path,
+    };
+
+    let mut child = Command::new(exe)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("failed to spawn resolver {:?}", exe))?;
+
+    {
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("resolver {:?} stdin unavailable", exe))?;
+        serde_json::to_writer(&mut stdin, &request)
+            .with_context(|| format!("failed to write request to resolver {:?}", exe))?;
+        stdin.write_all(b"\n")?;
+    }
+
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("resolver {:?} failed to run", exe))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let response_line = stdout.lines().find(|line| !line.trim().is_empty());
+
+    let response = match response_line {
+        Some(line) => serde_json::from_str::<ResolveResponse>(line)
+            .with_context(|| format!("resolver {:?} produced an invalid response: {}", exe, line))?,
+        None => {
+            debug!("resolver {:?} produced no output, falling back to static command", exe);
+            return Ok(ResolvedAction::Fallback);
+        }
+    };
+
+    match response {
+        ResolveResponse::Forward { mailslot } => Ok(ResolvedAction::Forward(mailslot)),
+        ResolveResponse::Launch { command } => Ok(ResolvedAction::Launch(command)),
+        ResolveResponse::Error { message } => bail!("resolver reported an error: {}", message),
+        ResolveResponse::Unsupported => Ok(ResolvedAction::Fallback),
+    }
+}
@@ -0,0 +1,306 @@
+// Copyright (c) Jørgen Tjernø <jorgen@tjer.no>. All rights reserved.
+//! A tiny `cfg()`-style boolean expression grammar for matching handler rules against a URL:
+//! `host = "x"`, `path_prefix = "/y"`, `query_has = "key"`, `query = "key=value"`, combined with
+//! `all(..)`, `any(..)`, and `not(..)`.
+use crate::platform::Rule;
+use anyhow::{anyhow, bail, Result};
+
+/// A parsed match expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    All(Vec<Expr>),
+    Any(Vec<Expr>),
+    Not(Box<Expr>),
+    Host(String),
+    PathPrefix(String),
+    QueryHas(String),
+    Query(String, String),
+}
+
+impl Expr {
+    /// Evaluate this expression against `url`.
+    pub fn matches(&self, url: &url::Url) -> bool {
+        match self {
+            Expr::All(exprs) => exprs.iter().all(|expr| expr.matches(url)),
+            Expr::Any(exprs) => exprs.iter().any(|expr| expr.matches(url)),
+            Expr::Not(expr) => !expr.matches(url),
+            Expr::Host(host) => url.host_str() == Some(host.as_str()),
+            Expr::PathPrefix(prefix) => url.path().starts_with(prefix.as_str()),
+            Expr::QueryHas(key) => url.query_pairs().any(|(k, _)| k == key.as_str()),
+            Expr::Query(key, value) => url
+                .query_pairs()
+                .any(|(k, v)| k == key.as_str() && v == value.as_str()),
+        }
+    }
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let trimmed = self.rest().trim_start();
+        self.pos = self.src.len() - trimmed.len();
+    }
+
+    fn expect_char(&mut self, expected: char) -> Result<()> {
+        self.skip_whitespace();
+        match self.rest().chars().next() {
+            Some(c) if c == expected => {
+                self.pos += c.len_utf8();
+                Ok(())
+            }
+            other => bail!(
+                "expected '{}', found {:?} in expression: {}",
+                expected,
+                other,
+                self.src
+            ),
+        }
+    }
+
+    fn peek_char(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.rest().chars().next()
+    }
+
+    fn parse_ident(&mut self) -> Result<&'a str> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        if end == 0 {
+            bail!("expected identifier in expression: {}", self.src);
+        }
+        let ident = &rest[..end];
+        self.pos += end;
+        Ok(ident)
+    }
+
+    fn parse_string(&mut self) -> Result<String> {
+        self.expect_char('"')?;
+        let rest = self.rest();
+        let end = rest
+            .find('"')
+            .ok_or_else(|| anyhow!("unterminated string in expression: {}", self.src))?;
+        let value = rest[..end].to_owned();
+        self.pos += end;
+        self.expect_char('"')?;
+        Ok(value)
+    }
+
+    fn parse_args(&mut self) -> Result<Vec<Expr>> {
+        self.expect_char('(')?;
+        let mut args = Vec::new();
+        if self.peek_char() != Some(')') {
+            loop {
+                args.push(self.parse_expr()?);
+                self.skip_whitespace();
+                if self.peek_char() == Some(',') {
+                    self.pos += 1;
+                } else {
+                    break;
+                }
+            }
+        }
+        self.expect_char(')')?;
+        Ok(args)
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr> {
+        let ident = self.parse_ident()?;
+        match ident {
+            "all" => Ok(Expr::All(self.parse_args()?)),
+            "any" => Ok(Expr::Any(self.parse_args()?)),
+            "not" => {
+                let mut args = self.parse_args()?;
+                if args.len() != 1 {
+                    bail!("not(..) takes exactly one argument in expression: {}", self.src);
+                }
+                Ok(Expr::Not(Box::new(args.remove(0))))
+            }
+            "host" => {
+                self.expect_char('=')?;
+                Ok(Expr::Host(self.parse_string()?))
+            }
+            "path_prefix" => {
+                self.expect_char('=')?;
+                Ok(Expr::PathPrefix(self.parse_string()?))
+            }
+            "query_has" => {
+                self.expect_char('=')?;
+                Ok(Expr::QueryHas(self.parse_string()?))
+            }
+            "query" => {
+                self.expect_char('=')?;
+                let raw = self.parse_string()?;
+                let (key, value) = raw.split_once('=').ok_or_else(|| {
+                    anyhow!("query = \"key=value\" expects a '=' in the quoted value: {}", raw)
+                })?;
+                Ok(Expr::Query(key.to_owned(), value.to_owned()))
+            }
+            other => bail!(
+                "unknown predicate or combinator '{}' in expression: {}",
+                other,
+                self.src
+            ),
+        }
+    }
+}
+
+/// Parse a match expression like `all(host = "a", not(query_has = "skip"))`.
+pub fn parse(src: &str) -> Result<Expr> {
+    let mut parser = Parser { src, pos: 0 };
+    let expr = parser.parse_expr()?;
+    parser.skip_whitespace();
+    if !parser.rest().is_empty() {
+        bail!("unexpected trailing input in expression: {}", src);
+    }
+    Ok(expr)
+}
+
+/// Pick the command line of the first rule whose expression matches `url` (a rule with no
+/// expression always matches, acting as the fallback).
+pub fn select<'a>(rules: &'a [Rule], url: &url::Url) -> Result<&'a [String]> {
+    for rule in rules {
+        let matches = match &rule.match_expr {
+            Some(expr) => parse(expr)?.matches(url),
+            None => true,
+        };
+        if matches {
+            return Ok(&rule.command);
+        }
+    }
+    bail!("no rule matched url {}", url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> url::Url {
+        url::Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn parses_host() {
+        assert_eq!(parse(r#"host = "example.com""#).unwrap(), Expr::Host("example.com".to_owned()));
+    }
+
+    #[test]
+    fn parses_path_prefix() {
+        assert_eq!(
+            parse(r#"path_prefix = "/assets""#).unwrap(),
+            Expr::PathPrefix("/assets".to_owned())
+        );
+    }
+
+    #[test]
+    fn parses_query_has() {
+        assert_eq!(parse(r#"query_has = "debug""#).unwrap(), Expr::QueryHas("debug".to_owned()));
+    }
+
+    #[test]
+    fn query_splits_on_first_equals_only() {
+        assert_eq!(
+            parse(r#"query = "key=value=with=equals""#).unwrap(),
+            Expr::Query("key".to_owned(), "value=with=equals".to_owned())
+        );
+    }
+
+    #[test]
+    fn query_requires_an_equals_sign() {
+        assert!(parse(r#"query = "no-equals-here""#).is_err());
+    }
+
+    #[test]
+    fn parses_nested_combinators() {
+        let expr = parse(r#"not(all(host = "a", any(query_has = "x", query_has = "y")))"#).unwrap();
+        assert_eq!(
+            expr,
+            Expr::Not(Box::new(Expr::All(vec![
+                Expr::Host("a".to_owned()),
+                Expr::Any(vec![
+                    Expr::QueryHas("x".to_owned()),
+                    Expr::QueryHas("y".to_owned()),
+                ]),
+            ])))
+        );
+    }
+
+    #[test]
+    fn not_requires_exactly_one_argument() {
+        assert!(parse(r#"not(host = "a", host = "b")"#).is_err());
+    }
+
+    #[test]
+    fn rejects_unterminated_strings() {
+        assert!(parse(r#"host = "unterminated"#).is_err());
+    }
+
+    #[test]
+    fn rejects_trailing_input() {
+        assert!(parse(r#"host = "a" garbage"#).is_err());
+    }
+
+    #[test]
+    fn matches_evaluate_against_a_url() {
+        let target = url("hermes://example.com/assets/thing?debug=1");
+
+        assert!(parse(r#"host = "example.com""#).unwrap().matches(&target));
+        assert!(parse(r#"path_prefix = "/assets""#).unwrap().matches(&target));
+        assert!(parse(r#"query_has = "debug""#).unwrap().matches(&target));
+        assert!(parse(r#"query = "debug=1""#).unwrap().matches(&target));
+        assert!(!parse(r#"host = "other.com""#).unwrap().matches(&target));
+        assert!(parse(r#"not(host = "other.com")"#).unwrap().matches(&target));
+        assert!(parse(r#"all(host = "example.com", query_has = "debug")"#)
+            .unwrap()
+            .matches(&target));
+        assert!(!parse(r#"all(host = "example.com", query_has = "missing")"#)
+            .unwrap()
+            .matches(&target));
+    }
+
+    #[test]
+    fn select_picks_first_matching_rule_and_falls_back() {
+        let target = url("hermes://example.com/assets/thing");
+        let rules = vec![
+            Rule {
+                match_expr: Some(r#"host = "other.com""#.to_owned()),
+                command: vec!["wrong".to_owned()],
+            },
+            Rule {
+                match_expr: Some(r#"path_prefix = "/assets""#.to_owned()),
+                command: vec!["right".to_owned()],
+            },
+            Rule {
+                match_expr: None,
+                command: vec!["fallback".to_owned()],
+            },
+        ];
+
+        assert_eq!(select(&rules, &target).unwrap(), &["right".to_owned()]);
+        assert_eq!(
+            select(&rules, &url("hermes://example.com/other")).unwrap(),
+            &["fallback".to_owned()]
+        );
+    }
+
+    #[test]
+    fn select_fails_when_nothing_matches() {
+        let target = url("hermes://example.com/assets/thing");
+        let rules = vec![Rule {
+            match_expr: Some(r#"host = "other.com""#.to_owned()),
+            command: vec!["wrong".to_owned()],
+        }];
+
+        assert!(select(&rules, &target).is_err());
+    }
+}